@@ -0,0 +1,312 @@
+//! `std::io::Read`/`Write` adapters for encrypting and decrypting data that
+//! doesn't fit in memory, such as large files.
+//!
+//! The stream is split into fixed-size blocks, each one encrypted and
+//! authenticated independently (using `Tag::Message` for every block but the
+//! last, which is tagged `Tag::Final`). Because every block carries its own
+//! authentication tag, reordering, dropping or duplicating blocks is
+//! detected on the read side, same as for the lower-level `Encryptor`/
+//! `Decryptor` API.
+
+use std::io::{self, Read, Write};
+
+use super::xchacha20poly1305::{Decryptor, Encryptor, Header, Key, Tag, ABYTES, HEADERBYTES};
+
+/// Default size, in bytes, of the plaintext blocks encrypted by
+/// `EncryptingWriter` and expected by `DecryptingReader`.
+pub const DEFAULT_BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Wraps a `Write` and encrypts everything written to it in fixed-size
+/// blocks, writing the stream `Header` first.
+///
+/// The stream isn't finalized until `finish` is called; dropping an
+/// `EncryptingWriter` without calling `finish` still emits a `Tag::Final`
+/// block best-effort, but any error doing so is silently discarded, same as
+/// `std::io::BufWriter`. Call `finish` explicitly to check for errors.
+pub struct EncryptingWriter<W: Write> {
+    inner: W,
+    encryptor: Option<Encryptor>,
+    block_size: usize,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> EncryptingWriter<W> {
+    /// Creates a new `EncryptingWriter`, writing the stream header to `inner`
+    /// immediately, using the default block size.
+    pub fn new(inner: W, key: &Key) -> io::Result<EncryptingWriter<W>> {
+        EncryptingWriter::with_block_size(inner, key, DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Like `new`, but encrypts in blocks of `block_size` plaintext bytes
+    /// instead of `DEFAULT_BLOCK_SIZE`. `block_size` must be greater than zero.
+    pub fn with_block_size(mut inner: W, key: &Key, block_size: usize) -> io::Result<EncryptingWriter<W>> {
+        if block_size == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "block_size must be greater than zero"));
+        }
+        let (encryptor, header) = Encryptor::init(key)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to initialize secretstream"))?;
+        inner.write_all(&header.0)?;
+        Ok(EncryptingWriter {
+            inner,
+            encryptor: Some(encryptor),
+            block_size,
+            buf: Vec::with_capacity(block_size),
+        })
+    }
+
+    /// Encrypts any buffered plaintext as the final, `Tag::Final`-tagged
+    /// block and writes it to the underlying writer. A no-op if the stream
+    /// has already been finalized.
+    fn finalize(&mut self) -> io::Result<()> {
+        if let Some(encryptor) = self.encryptor.take() {
+            let c = encryptor
+                .finalize(&self.buf, None)
+                .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to finalize stream"))?;
+            self.buf.clear();
+            self.inner.write_all(&c)?;
+        }
+        Ok(())
+    }
+
+    /// Finalizes the stream (emitting the `Tag::Final` block) and flushes
+    /// the underlying writer. Unlike `flush`, this ends the stream, so it
+    /// must be called once, after all plaintext has been written.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.finalize()?;
+        self.inner.flush()
+    }
+
+    fn encrypt_full_block(&mut self) -> io::Result<()> {
+        let c = self
+            .encryptor
+            .as_mut()
+            .expect("write after finish")
+            .message(&self.buf, None)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to encrypt block"))?;
+        self.inner.write_all(&c)?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for EncryptingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = buf.len();
+        let mut buf = buf;
+        while !buf.is_empty() {
+            let space = self.block_size - self.buf.len();
+            let n = space.min(buf.len());
+            self.buf.extend_from_slice(&buf[..n]);
+            buf = &buf[n..];
+            if self.buf.len() == self.block_size {
+                self.encrypt_full_block()?;
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for EncryptingWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.finalize();
+    }
+}
+
+/// Wraps a `Read` and decrypts fixed-size blocks from it, reading and
+/// consuming the stream `Header` first.
+///
+/// Returns an `io::Error` of kind `UnexpectedEof` if the underlying reader
+/// ends on a block boundary before a `Tag::Final` block has been seen, which
+/// indicates the stream was truncated. A stream cut off in the middle of a
+/// block fails authentication instead, and is reported as `InvalidData`.
+pub struct DecryptingReader<R: Read> {
+    inner: R,
+    decryptor: Decryptor,
+    block_size: usize,
+    plaintext: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    /// Creates a new `DecryptingReader`, reading and consuming the stream
+    /// header from `inner` immediately, using the default block size.
+    pub fn new(inner: R, key: &Key) -> io::Result<DecryptingReader<R>> {
+        DecryptingReader::with_block_size(inner, key, DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Like `new`, but expects blocks of `block_size` plaintext bytes instead
+    /// of `DEFAULT_BLOCK_SIZE`. `block_size` must be greater than zero.
+    pub fn with_block_size(mut inner: R, key: &Key, block_size: usize) -> io::Result<DecryptingReader<R>> {
+        if block_size == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "block_size must be greater than zero"));
+        }
+        let mut header_bytes = vec![0u8; HEADERBYTES];
+        inner.read_exact(&mut header_bytes)?;
+        let header = Header::from_slice(&header_bytes)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed stream header"))?;
+        let decryptor = Decryptor::init(&header, key)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "failed to initialize secretstream"))?;
+        Ok(DecryptingReader {
+            inner,
+            decryptor,
+            block_size,
+            plaintext: Vec::new(),
+            pos: 0,
+            done: false,
+        })
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let mut c = vec![0u8; self.block_size + ABYTES];
+        let mut filled = 0;
+        while filled < c.len() {
+            let n = self.inner.read(&mut c[filled..])?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        if filled == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "stream ended before a final block was seen",
+            ));
+        }
+        c.truncate(filled);
+        let (m, tag) = self
+            .decryptor
+            .decrypt(&c, None)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "failed to authenticate block"))?;
+        if tag == Tag::Final {
+            self.done = true;
+        }
+        self.plaintext = m;
+        self.pos = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.plaintext.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let n = (self.plaintext.len() - self.pos).min(buf.len());
+        buf[..n].copy_from_slice(&self.plaintext[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crypto::secretstream::xchacha20poly1305::gen_key;
+    use std::cell::RefCell;
+    use std::io::{Read, Write};
+    use std::rc::Rc;
+
+    /// A `Write` sink that also lets the test read back what was written
+    /// after `EncryptingWriter` (which doesn't hand its inner writer back
+    /// from `finish`) has consumed its own handle.
+    #[derive(Clone)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl SharedBuf {
+        fn new() -> SharedBuf {
+            SharedBuf(Rc::new(RefCell::new(Vec::new())))
+        }
+
+        fn contents(&self) -> Vec<u8> {
+            self.0.borrow().clone()
+        }
+    }
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.borrow_mut().flush()
+        }
+    }
+
+    fn seal(key: &Key, block_size: usize, message: &[u8]) -> Vec<u8> {
+        let sink = SharedBuf::new();
+        let mut w = EncryptingWriter::with_block_size(sink.clone(), key, block_size).unwrap();
+        w.write_all(message).unwrap();
+        w.finish().unwrap();
+        sink.contents()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        ::init().unwrap();
+        let key = gen_key();
+        let message = b"a message too large to fit in one block, or not";
+        let ciphertext = seal(&key, 8, message);
+
+        let mut r = DecryptingReader::with_block_size(&ciphertext[..], &key, 8).unwrap();
+        let mut plaintext = Vec::new();
+        r.read_to_end(&mut plaintext).unwrap();
+        assert_eq!(plaintext, &message[..]);
+    }
+
+    #[test]
+    fn test_round_trip_on_block_boundary() {
+        ::init().unwrap();
+        let key = gen_key();
+        let message = vec![0x42; 16];
+        let ciphertext = seal(&key, 8, &message);
+
+        let mut r = DecryptingReader::with_block_size(&ciphertext[..], &key, 8).unwrap();
+        let mut plaintext = Vec::new();
+        r.read_to_end(&mut plaintext).unwrap();
+        assert_eq!(plaintext, message);
+    }
+
+    #[test]
+    fn test_truncated_stream_is_rejected() {
+        ::init().unwrap();
+        let key = gen_key();
+        let message = vec![0x42; 16];
+        let mut ciphertext = seal(&key, 8, &message);
+        let last = ciphertext.len() - 1;
+        ciphertext.truncate(last);
+
+        let mut r = DecryptingReader::with_block_size(&ciphertext[..], &key, 8).unwrap();
+        let mut plaintext = Vec::new();
+        assert!(r.read_to_end(&mut plaintext).is_err());
+    }
+
+    #[test]
+    fn test_tampered_block_is_rejected() {
+        ::init().unwrap();
+        let key = gen_key();
+        let message = vec![0x42; 16];
+        let mut ciphertext = seal(&key, 8, &message);
+        let last = ciphertext.len() - 1;
+        ciphertext[last] ^= 1;
+
+        let mut r = DecryptingReader::with_block_size(&ciphertext[..], &key, 8).unwrap();
+        let mut plaintext = Vec::new();
+        assert!(r.read_to_end(&mut plaintext).is_err());
+    }
+
+    #[test]
+    fn test_zero_block_size_is_rejected() {
+        ::init().unwrap();
+        let key = gen_key();
+        assert!(EncryptingWriter::with_block_size(Vec::new(), &key, 0).is_err());
+        assert!(DecryptingReader::with_block_size(&b""[..], &key, 0).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+//! A key-rotating wrapper around the secretstream module.
+//!
+//! `KeyRing` lets long-lived systems rotate their secretstream key without
+//! losing the ability to decrypt streams produced under an older key: every
+//! stream produced via `start_stream` embeds the id of the key that produced
+//! it, so `open` can later look up the right key regardless of how many
+//! times the ring has rotated since.
+
+use super::xchacha20poly1305::{gen_key, Decryptor, Encryptor, Header, Key, HEADERBYTES};
+
+/// Number of bytes used to prefix a `KeyRing`-produced header with the id of
+/// the key it was produced under.
+pub const KEY_ID_BYTES: usize = 4;
+
+/// Error returned by `KeyRing::open`.
+#[derive(Debug, PartialEq)]
+pub enum KeyRingError {
+    /// `header` didn't contain enough bytes for a key id and a stream header.
+    Truncated,
+    /// The key id embedded in the header isn't known to this `KeyRing`.
+    UnknownKeyId(u32),
+    /// The embedded key id was known, but the stream header itself was invalid.
+    Crypto,
+}
+
+/// Holds a set of keys addressed by a small numeric id, one of which is
+/// designated the primary key used to start new streams.
+pub struct KeyRing {
+    keys: Vec<(u32, Key)>,
+    primary: u32,
+}
+
+impl KeyRing {
+    /// Creates a `KeyRing` whose only key is `key`, identified by `key_id`
+    /// and set as primary.
+    pub fn new(key_id: u32, key: Key) -> KeyRing {
+        KeyRing { keys: vec![(key_id, key)], primary: key_id }
+    }
+
+    /// Adds `key` under `key_id` (replacing any key already using that id),
+    /// without changing the primary key.
+    pub fn add_key(&mut self, key_id: u32, key: Key) {
+        self.keys.retain(|&(id, _)| id != key_id);
+        self.keys.push((key_id, key));
+    }
+
+    /// Designates the key identified by `key_id` as the primary key used by
+    /// `start_stream`. Returns `Err` if no such key has been added.
+    pub fn set_primary(&mut self, key_id: u32) -> Result<(), ()> {
+        if self.key(key_id).is_none() {
+            return Err(());
+        }
+        self.primary = key_id;
+        Ok(())
+    }
+
+    /// Generates a fresh key, assigns it an id one greater than the highest
+    /// id currently in the ring (starting at `0`), adds it, and makes it
+    /// primary. Returns the new key's id.
+    pub fn rotate(&mut self) -> u32 {
+        let key_id = self.keys.iter().map(|&(id, _)| id).max().map_or(0, |id| id + 1);
+        self.keys.push((key_id, gen_key()));
+        self.primary = key_id;
+        key_id
+    }
+
+    fn key(&self, key_id: u32) -> Option<&Key> {
+        self.keys.iter().find(|&&(id, _)| id == key_id).map(|&(_, ref key)| key)
+    }
+
+    /// Initializes a new stream under the primary key, returning an
+    /// `Encryptor` together with a header prefixed with the 4-byte
+    /// big-endian primary key id, so `open` can later find the right key.
+    pub fn start_stream(&self) -> Result<(Encryptor, Vec<u8>), ()> {
+        let key = self.key(self.primary).ok_or(())?;
+        let (encryptor, header) = Encryptor::init(key)?;
+        let mut out = Vec::with_capacity(KEY_ID_BYTES + HEADERBYTES);
+        out.extend_from_slice(&self.primary.to_be_bytes());
+        out.extend_from_slice(&header.0);
+        Ok((encryptor, out))
+    }
+
+    /// Reads the key id embedded in the front of `header` and initializes a
+    /// `Decryptor` with the matching key.
+    pub fn open(&self, header: &[u8]) -> Result<Decryptor, KeyRingError> {
+        if header.len() < KEY_ID_BYTES + HEADERBYTES {
+            return Err(KeyRingError::Truncated);
+        }
+        let (id_bytes, header_bytes) = header.split_at(KEY_ID_BYTES);
+        let key_id = u32::from_be_bytes([id_bytes[0], id_bytes[1], id_bytes[2], id_bytes[3]]);
+        let key = self.key(key_id).ok_or(KeyRingError::UnknownKeyId(key_id))?;
+        let header = Header::from_slice(header_bytes).ok_or(KeyRingError::Crypto)?;
+        Decryptor::init(&header, key).map_err(|_| KeyRingError::Crypto)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crypto::secretstream::xchacha20poly1305::Tag;
+
+    #[test]
+    fn test_start_stream_open_round_trip() {
+        ::init().unwrap();
+        let mut ring = KeyRing::new(0, gen_key());
+        let m = b"a message to protect";
+
+        let (encryptor, header) = ring.start_stream().unwrap();
+        let c = encryptor.finalize(m, None).unwrap();
+
+        let mut decryptor = ring.open(&header).unwrap();
+        let (out, tag) = decryptor.decrypt(&c, None).unwrap();
+        assert_eq!(out, &m[..]);
+        assert_eq!(tag, Tag::Final);
+    }
+
+    #[test]
+    fn test_open_after_rotation_finds_old_key() {
+        ::init().unwrap();
+        let mut ring = KeyRing::new(0, gen_key());
+        let m = b"a message to protect";
+
+        // Start a stream under key 0, then rotate before decrypting it.
+        let (encryptor, header) = ring.start_stream().unwrap();
+        let c = encryptor.finalize(m, None).unwrap();
+        let new_id = ring.rotate();
+        assert_ne!(new_id, 0);
+
+        let mut decryptor = ring.open(&header).unwrap();
+        let (out, _tag) = decryptor.decrypt(&c, None).unwrap();
+        assert_eq!(out, &m[..]);
+    }
+
+    #[test]
+    fn test_open_rejects_unknown_key_id() {
+        ::init().unwrap();
+        let ring = KeyRing::new(0, gen_key());
+        let other = KeyRing::new(1, gen_key());
+
+        let (encryptor, header) = other.start_stream().unwrap();
+        let _ = encryptor;
+        assert_eq!(ring.open(&header), Err(KeyRingError::UnknownKeyId(1)));
+    }
+
+    #[test]
+    fn test_open_rejects_truncated_header() {
+        ::init().unwrap();
+        let ring = KeyRing::new(0, gen_key());
+        let (_encryptor, header) = ring.start_stream().unwrap();
+        assert_eq!(ring.open(&header[..KEY_ID_BYTES]), Err(KeyRingError::Truncated));
+    }
+
+    #[test]
+    fn test_set_primary_rejects_unknown_key_id() {
+        let mut ring = KeyRing::new(0, gen_key());
+        assert!(ring.set_primary(42).is_err());
+    }
+}
@@ -0,0 +1,53 @@
+//! Secret-key authenticated encryption of a sequence of messages, or streams.
+//!
+//! This API encrypts a sequence of messages, or a single message split into
+//! an arbitrary number of chunks, using a secret key, with the following
+//! properties:
+//!
+//! * Messages cannot be truncated, removed, reordered or duplicated without
+//!   this being detected.
+//! * Each message can include additional data (ex: timestamp, protocol
+//!   version) in the computation of the authentication tag.
+//! * Messages can have different sizes.
+//! * There is no limit to the total length of the stream, or to the number
+//!   of individual messages.
+
+#[macro_use]
+mod secretstream_macros;
+
+pub mod io;
+pub mod keyring;
+
+#[cfg(feature = "tokio")]
+pub mod tokio_io;
+
+/// The `xchacha20poly1305` implementation.
+pub mod xchacha20poly1305 {
+    use ffi::{crypto_secretstream_xchacha20poly1305_init_pull,
+              crypto_secretstream_xchacha20poly1305_init_push,
+              crypto_secretstream_xchacha20poly1305_pull,
+              crypto_secretstream_xchacha20poly1305_push,
+              crypto_secretstream_xchacha20poly1305_rekey,
+              crypto_secretstream_xchacha20poly1305_state,
+              crypto_secretstream_xchacha20poly1305_ABYTES,
+              crypto_secretstream_xchacha20poly1305_HEADERBYTES,
+              crypto_secretstream_xchacha20poly1305_KEYBYTES,
+              crypto_secretstream_xchacha20poly1305_TAG_FINAL,
+              crypto_secretstream_xchacha20poly1305_TAG_MESSAGE,
+              crypto_secretstream_xchacha20poly1305_TAG_PUSH,
+              crypto_secretstream_xchacha20poly1305_TAG_REKEY};
+
+    stream_module!(crypto_secretstream_xchacha20poly1305_state,
+                    crypto_secretstream_xchacha20poly1305_init_push,
+                    crypto_secretstream_xchacha20poly1305_push,
+                    crypto_secretstream_xchacha20poly1305_init_pull,
+                    crypto_secretstream_xchacha20poly1305_pull,
+                    crypto_secretstream_xchacha20poly1305_rekey,
+                    crypto_secretstream_xchacha20poly1305_KEYBYTES,
+                    crypto_secretstream_xchacha20poly1305_HEADERBYTES,
+                    crypto_secretstream_xchacha20poly1305_ABYTES,
+                    crypto_secretstream_xchacha20poly1305_TAG_MESSAGE,
+                    crypto_secretstream_xchacha20poly1305_TAG_PUSH,
+                    crypto_secretstream_xchacha20poly1305_TAG_REKEY,
+                    crypto_secretstream_xchacha20poly1305_TAG_FINAL);
+}
@@ -157,6 +157,27 @@ impl Encryptor {
         self._push(m, ad, TAG_FINAL)
     }
 
+    /// Like `message`, but writes the ciphertext into the caller-supplied `out`
+    /// buffer instead of allocating a new `Vec`, returning the number of bytes written.
+    /// Returns `Err` if `out` is smaller than `m.len() + ABYTES`.
+    pub fn message_into(&mut self, m: &[u8], ad: Option<&[u8]>, out: &mut [u8]) -> Result<usize, ()> {
+        self._push_into(m, ad, TAG_MESSAGE, out)
+    }
+
+    /// Like `push`, but writes the ciphertext into the caller-supplied `out`
+    /// buffer instead of allocating a new `Vec`, returning the number of bytes written.
+    /// Returns `Err` if `out` is smaller than `m.len() + ABYTES`.
+    pub fn push_into(&mut self, m: &[u8], ad: Option<&[u8]>, out: &mut [u8]) -> Result<usize, ()> {
+        self._push_into(m, ad, TAG_PUSH, out)
+    }
+
+    /// Like `finalize`, but writes the ciphertext into the caller-supplied `out`
+    /// buffer instead of allocating a new `Vec`, returning the number of bytes written.
+    /// Returns `Err` if `out` is smaller than `m.len() + ABYTES`.
+    pub fn finalize_into(mut self, m: &[u8], ad: Option<&[u8]>, out: &mut [u8]) -> Result<usize, ()> {
+        self._push_into(m, ad, TAG_FINAL, out)
+    }
+
     /// Explicit rekeying, updates the state, but doesn't add any information about the key change to the stream.
     /// If this function is used to create an encrypted stream, the decryption process must call that function at the exact same stream location.
     pub fn rekey(&mut self) {
@@ -189,6 +210,33 @@ impl Encryptor {
         }
         Ok(c)
     }
+
+    /// Encrypts a message `m` using the `state` and the `tag`, writing the result into `out`.
+    /// Additional data ad of length adlen can be included in the computation of the authentication tag.
+    /// If no additional data is required, ad can be None.
+    /// Returns the number of bytes written, or `Err` if `out` is too small to hold the ciphertext.
+    fn _push_into(&mut self, m: &[u8], ad: Option<&[u8]>, tag: u8, out: &mut [u8]) -> Result<usize, ()> {
+        if out.len() < m.len() + ABYTES {
+            return Err(());
+        }
+        let (ad_p, ad_len) = ad.map(|ad| (ad.as_ptr(), ad.len() as c_ulonglong)).unwrap_or((0 as *const _, 0));
+        let mut clen = out.len() as c_ulonglong;
+
+        unsafe {
+            let err = $push_name(&mut self.0,
+                                 out.as_mut_ptr(),
+                                 &mut clen,
+                                 m.as_ptr(),
+                                 m.len() as c_ulonglong,
+                                 ad_p,
+                                 ad_len,
+                                 tag);
+            if err != 0 {
+                return Err(());
+            }
+        }
+        Ok(clen as usize)
+    }
 }
 
 /// `Decryptor` contains the state for multi-part (streaming) computations. This allows the caller
@@ -242,6 +290,35 @@ impl Decryptor {
         Ok((m, tag))
     }
 
+    /// Like `decrypt`, but writes the plaintext into the caller-supplied `out`
+    /// buffer instead of allocating a new `Vec`, returning the number of bytes
+    /// written together with the tag.
+    /// Returns `Err` if `out` is smaller than `c.len() - ABYTES`.
+    pub fn decrypt_into(&mut self, c: &[u8], ad: Option<&[u8]>, out: &mut [u8]) -> Result<(usize, Tag), ()> {
+        if c.len() < ABYTES || out.len() < c.len() - ABYTES {
+            return Err(());
+        }
+        let (ad_p, ad_len) = ad.map(|ad| (ad.as_ptr(), ad.len() as c_ulonglong)).unwrap_or((0 as *const _, 0));
+        let mut mlen = out.len() as c_ulonglong;
+        let mut tag: u8 = 0;
+
+        unsafe {
+            if $pull_name(&mut self.state,
+                          out.as_mut_ptr(),
+                          &mut mlen,
+                          &mut tag,
+                          c.as_ptr(),
+                          c.len() as c_ulonglong,
+                          ad_p,
+                          ad_len) != 0 {
+                return Err(());
+            }
+        }
+        let tag = _tag_from_byte(tag)?;
+        if tag == Tag::Final { self.flag_finalized = true; }
+        Ok((mlen as usize, tag))
+    }
+
     /// Explicit rekeying, updates the state, but doesn't add any information about the key change to the stream.
     /// If this function is used to create an encrypted stream,
     /// the decryption process must call that function at the exact same stream location.
@@ -257,4 +334,131 @@ impl Decryptor {
     }
 }
 
+/// Encrypts `message` as a single-message stream, returning `header || ciphertext`.
+/// Additional data ad can be included in the computation of the authentication tag.
+/// If no additional data is required, ad can be None.
+///
+/// This is a convenience wrapper around `Encryptor` for callers who have the
+/// whole message in memory at once: it initializes a stream, encrypts `message`
+/// as the one and only `Final`-tagged chunk, and concatenates the header onto
+/// the ciphertext so both can be shipped around as a single blob.
+pub fn seal(message: &[u8], ad: Option<&[u8]>, key: &Key) -> Vec<u8> {
+    let (encryptor, header) = Encryptor::init(key).expect("failed to initialize secretstream");
+    let mut c = encryptor.finalize(message, ad).expect("failed to encrypt message");
+    let mut out = Vec::with_capacity(HEADERBYTES + c.len());
+    out.extend_from_slice(&header.0);
+    out.append(&mut c);
+    out
+}
+
+/// Decrypts a blob produced by `seal`, returning the plaintext.
+/// Additional data ad must match the ad that was passed to `seal`, or decryption fails.
+///
+/// Returns `Err(())` if `ciphertext` is too short to contain a header, if the
+/// authentication tag doesn't verify, or if the stream doesn't end on a
+/// `Tag::Final` message, which would indicate a truncated or tampered stream.
+pub fn open(ciphertext: &[u8], ad: Option<&[u8]>, key: &Key) -> Result<Vec<u8>, ()> {
+    if ciphertext.len() < HEADERBYTES + ABYTES {
+        return Err(());
+    }
+    let (header_bytes, c) = ciphertext.split_at(HEADERBYTES);
+    let header = Header::from_slice(header_bytes).ok_or(())?;
+    let mut decryptor = Decryptor::init(&header, key)?;
+    let (m, tag) = decryptor.decrypt(c, ad)?;
+    if tag == Tag::Final {
+        Ok(m)
+    } else {
+        Err(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_seal_open() {
+        ::init().unwrap();
+        let key = gen_key();
+        let m = b"a message to protect";
+        let c = seal(m, None, &key);
+        assert_eq!(open(&c, None, &key).unwrap(), &m[..]);
+    }
+
+    #[test]
+    fn test_seal_open_with_ad() {
+        ::init().unwrap();
+        let key = gen_key();
+        let m = b"a message to protect";
+        let ad = b"associated data";
+        let c = seal(m, Some(ad), &key);
+        assert_eq!(open(&c, Some(ad), &key).unwrap(), &m[..]);
+        assert!(open(&c, None, &key).is_err());
+    }
+
+    #[test]
+    fn test_open_rejects_truncated_ciphertext() {
+        ::init().unwrap();
+        let key = gen_key();
+        let c = seal(b"a message to protect", None, &key);
+        for len in 0..HEADERBYTES + ABYTES {
+            assert!(open(&c[..len], None, &key).is_err());
+        }
+    }
+
+    #[test]
+    fn test_open_rejects_tampered_ciphertext() {
+        ::init().unwrap();
+        let key = gen_key();
+        let mut c = seal(b"a message to protect", None, &key);
+        let last = c.len() - 1;
+        c[last] ^= 1;
+        assert!(open(&c, None, &key).is_err());
+    }
+
+    #[test]
+    fn test_message_into_decrypt_into_round_trip() {
+        ::init().unwrap();
+        let key = gen_key();
+        let m = b"a message to protect";
+
+        let (mut encryptor, header) = Encryptor::init(&key).unwrap();
+        let mut c = vec![0u8; m.len() + ABYTES];
+        let n = encryptor.message_into(m, None, &mut c).unwrap();
+        assert_eq!(n, c.len());
+
+        let mut decryptor = Decryptor::init(&header, &key).unwrap();
+        let mut out = vec![0u8; m.len()];
+        let (n, tag) = decryptor.decrypt_into(&c, None, &mut out).unwrap();
+        assert_eq!(n, m.len());
+        assert_eq!(&out[..n], &m[..]);
+        assert_eq!(tag, Tag::Message);
+    }
+
+    #[test]
+    fn test_push_into_and_finalize_into_too_small_buffer() {
+        ::init().unwrap();
+        let key = gen_key();
+        let m = b"a message to protect";
+        let (mut encryptor, _header) = Encryptor::init(&key).unwrap();
+
+        let mut too_small = vec![0u8; m.len() + ABYTES - 1];
+        assert!(encryptor.push_into(m, None, &mut too_small).is_err());
+        assert!(encryptor.finalize_into(m, None, &mut too_small).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_into_too_small_buffer() {
+        ::init().unwrap();
+        let key = gen_key();
+        let m = b"a message to protect";
+        let (encryptor, header) = Encryptor::init(&key).unwrap();
+        let c = encryptor.finalize(m, None).unwrap();
+
+        let mut decryptor = Decryptor::init(&header, &key).unwrap();
+        let mut too_small = vec![0u8; m.len() - 1];
+        assert!(decryptor.decrypt_into(&c, None, &mut too_small).is_err());
+    }
+}
+
 ));
\ No newline at end of file
@@ -0,0 +1,404 @@
+//! `tokio::io::AsyncRead`/`AsyncWrite` adapters, for encrypting and
+//! decrypting network streams and files without blocking.
+//!
+//! These mirror the synchronous adapters in `io`, with the same fixed-size
+//! block chunking and the same `Tag::Final`-on-last-block framing, but drive
+//! the underlying `AsyncRead`/`AsyncWrite` through `poll_*` instead of
+//! blocking calls. A partially-filled plaintext block, or a partially
+//! flushed ciphertext block, is retained between poll calls.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::io::DEFAULT_BLOCK_SIZE;
+use super::xchacha20poly1305::{Decryptor, Encryptor, Header, Key, Tag, ABYTES, HEADERBYTES};
+
+/// Wraps an `AsyncWrite` and encrypts everything written to it in fixed-size
+/// blocks, transparently writing the stream `Header` before the first block.
+///
+/// The final block is tagged `Tag::Final` on `poll_shutdown`, so the adapter
+/// must be shut down (not just dropped) for the peer to be able to tell the
+/// stream ended cleanly.
+pub struct AsyncEncryptingWriter<W> {
+    inner: W,
+    encryptor: Option<Encryptor>,
+    block_size: usize,
+    plaintext: Vec<u8>,
+    pending: Vec<u8>,
+    pending_pos: usize,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncEncryptingWriter<W> {
+    /// Creates a new `AsyncEncryptingWriter` using the default block size.
+    /// The header is queued to be written transparently on the first poll.
+    pub fn new(inner: W, key: &Key) -> Result<AsyncEncryptingWriter<W>, ()> {
+        AsyncEncryptingWriter::with_block_size(inner, key, DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Like `new`, but encrypts in blocks of `block_size` plaintext bytes
+    /// instead of `DEFAULT_BLOCK_SIZE`. `block_size` must be greater than zero.
+    pub fn with_block_size(inner: W, key: &Key, block_size: usize) -> Result<AsyncEncryptingWriter<W>, ()> {
+        if block_size == 0 {
+            return Err(());
+        }
+        let (encryptor, header) = Encryptor::init(key)?;
+        Ok(AsyncEncryptingWriter {
+            inner,
+            encryptor: Some(encryptor),
+            block_size,
+            plaintext: Vec::with_capacity(block_size),
+            pending: header.0.to_vec(),
+            pending_pos: 0,
+        })
+    }
+
+    /// Drains `self.pending[self.pending_pos..]` into `self.inner`, returning
+    /// `Poll::Ready(Ok(()))` once it is all flushed.
+    fn poll_drain_pending(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        while self.pending_pos < self.pending.len() {
+            let inner = Pin::new(&mut self.inner);
+            match inner.poll_write(cx, &self.pending[self.pending_pos..]) {
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write whole block")));
+                }
+                Poll::Ready(Ok(n)) => self.pending_pos += n,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        self.pending.clear();
+        self.pending_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for AsyncEncryptingWriter<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        match this.poll_drain_pending(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        }
+
+        let space = this.block_size - this.plaintext.len();
+        let n = space.min(buf.len());
+        this.plaintext.extend_from_slice(&buf[..n]);
+
+        if this.plaintext.len() == this.block_size {
+            let c = this
+                .encryptor
+                .as_mut()
+                .expect("write after shutdown")
+                .message(&this.plaintext, None)
+                .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to encrypt block"))?;
+            this.plaintext.clear();
+            this.pending = c;
+            this.pending_pos = 0;
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        match this.poll_drain_pending(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.inner).poll_flush(cx),
+            other => other,
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let Some(encryptor) = this.encryptor.take() {
+            let c = encryptor
+                .finalize(&this.plaintext, None)
+                .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to finalize stream"))?;
+            this.plaintext.clear();
+            this.pending.extend_from_slice(&c);
+        }
+        match this.poll_drain_pending(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.inner).poll_shutdown(cx),
+            other => other,
+        }
+    }
+}
+
+enum ReadState {
+    Header { buf: Vec<u8> },
+    Body { decryptor: Decryptor, done: bool },
+}
+
+/// Wraps an `AsyncRead` and decrypts fixed-size blocks from it, transparently
+/// reading and consuming the stream `Header` on the first poll.
+///
+/// Surfaces an `io::Error` of kind `UnexpectedEof` if the underlying reader
+/// ends on a block boundary before a `Tag::Final` block has been seen. A
+/// stream cut off in the middle of a block fails authentication instead, and
+/// is reported as `InvalidData`.
+pub struct AsyncDecryptingReader<R> {
+    inner: R,
+    pending_key: Option<Key>,
+    key_block_size: usize,
+    state: ReadState,
+    ciphertext: Vec<u8>,
+    plaintext: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: AsyncRead + Unpin> AsyncDecryptingReader<R> {
+    /// Creates a new `AsyncDecryptingReader` using the default block size.
+    pub fn new(inner: R, key: Key) -> Result<AsyncDecryptingReader<R>, ()> {
+        AsyncDecryptingReader::with_block_size(inner, key, DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Like `new`, but expects blocks of `block_size` plaintext bytes instead
+    /// of `DEFAULT_BLOCK_SIZE`. `block_size` must be greater than zero.
+    pub fn with_block_size(inner: R, key: Key, block_size: usize) -> Result<AsyncDecryptingReader<R>, ()> {
+        if block_size == 0 {
+            return Err(());
+        }
+        Ok(AsyncDecryptingReader {
+            inner,
+            pending_key: Some(key),
+            key_block_size: block_size,
+            state: ReadState::Header { buf: Vec::with_capacity(HEADERBYTES) },
+            ciphertext: Vec::new(),
+            plaintext: Vec::new(),
+            pos: 0,
+        })
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for AsyncDecryptingReader<R> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.pos < this.plaintext.len() {
+                let n = (this.plaintext.len() - this.pos).min(buf.remaining());
+                buf.put_slice(&this.plaintext[this.pos..this.pos + n]);
+                this.pos += n;
+                return Poll::Ready(Ok(()));
+            }
+
+            match &mut this.state {
+                ReadState::Body { done: true, .. } => return Poll::Ready(Ok(())),
+                ReadState::Header { buf: header_buf } => {
+                    let want = HEADERBYTES - header_buf.len();
+                    let mut tmp = vec![0u8; want];
+                    let mut rb = ReadBuf::new(&mut tmp);
+                    match Pin::new(&mut this.inner).poll_read(cx, &mut rb) {
+                        Poll::Ready(Ok(())) => {
+                            let n = rb.filled().len();
+                            if n == 0 {
+                                return Poll::Ready(Err(io::Error::new(
+                                    io::ErrorKind::UnexpectedEof,
+                                    "stream ended before a header was read",
+                                )));
+                            }
+                            header_buf.extend_from_slice(&rb.filled()[..n]);
+                            if header_buf.len() == HEADERBYTES {
+                                let header = Header::from_slice(header_buf).ok_or_else(|| {
+                                    io::Error::new(io::ErrorKind::InvalidData, "malformed stream header")
+                                })?;
+                                let key = this.pending_key.take().expect("key consumed twice");
+                                let decryptor = Decryptor::init(&header, &key).map_err(|_| {
+                                    io::Error::new(io::ErrorKind::InvalidData, "failed to initialize secretstream")
+                                })?;
+                                this.state = ReadState::Body { decryptor, done: false };
+                            }
+                        }
+                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+                ReadState::Body { decryptor, done } => {
+                    let want = this.key_block_size + ABYTES - this.ciphertext.len();
+                    let mut tmp = vec![0u8; want];
+                    let mut rb = ReadBuf::new(&mut tmp);
+                    match Pin::new(&mut this.inner).poll_read(cx, &mut rb) {
+                        Poll::Ready(Ok(())) => {
+                            let n = rb.filled().len();
+                            if n == 0 {
+                                if this.ciphertext.is_empty() {
+                                    return Poll::Ready(Err(io::Error::new(
+                                        io::ErrorKind::UnexpectedEof,
+                                        "stream ended before a final block was seen",
+                                    )));
+                                }
+                                let (m, tag) = decryptor.decrypt(&this.ciphertext, None).map_err(|_| {
+                                    io::Error::new(io::ErrorKind::InvalidData, "failed to authenticate block")
+                                })?;
+                                *done = tag == Tag::Final;
+                                this.plaintext = m;
+                                this.pos = 0;
+                                this.ciphertext.clear();
+                            } else {
+                                this.ciphertext.extend_from_slice(&rb.filled()[..n]);
+                                if this.ciphertext.len() == this.key_block_size + ABYTES {
+                                    let (m, tag) = decryptor.decrypt(&this.ciphertext, None).map_err(|_| {
+                                        io::Error::new(io::ErrorKind::InvalidData, "failed to authenticate block")
+                                    })?;
+                                    *done = tag == Tag::Final;
+                                    this.plaintext = m;
+                                    this.pos = 0;
+                                    this.ciphertext.clear();
+                                }
+                            }
+                        }
+                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crypto::secretstream::xchacha20poly1305::gen_key;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// An `AsyncWrite` sink that also lets the test read back what was
+    /// written after `AsyncEncryptingWriter` has consumed its own handle.
+    #[derive(Clone)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl SharedBuf {
+        fn new() -> SharedBuf {
+            SharedBuf(Rc::new(RefCell::new(Vec::new())))
+        }
+
+        fn contents(&self) -> Vec<u8> {
+            self.0.borrow().clone()
+        }
+    }
+
+    impl AsyncWrite for SharedBuf {
+        fn poll_write(self: Pin<&mut Self>, _cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Wraps an inner `AsyncRead`/`AsyncWrite` and returns `Poll::Pending`
+    /// once before forwarding to it, so tests can exercise the adapters'
+    /// handling of a pending poll in the middle of a block.
+    struct Flaky<T> {
+        inner: T,
+        pending_once: bool,
+    }
+
+    impl<T> Flaky<T> {
+        fn new(inner: T) -> Flaky<T> {
+            Flaky { inner, pending_once: true }
+        }
+
+        fn poll_once(&mut self, cx: &mut Context) -> bool {
+            if self.pending_once {
+                self.pending_once = false;
+                cx.waker().wake_by_ref();
+                true
+            } else {
+                false
+            }
+        }
+    }
+
+    impl<T: AsyncRead + Unpin> AsyncRead for Flaky<T> {
+        fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<io::Result<()>> {
+            if self.poll_once(cx) {
+                return Poll::Pending;
+            }
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl<T: AsyncWrite + Unpin> AsyncWrite for Flaky<T> {
+        fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+            if self.poll_once(cx) {
+                return Poll::Pending;
+            }
+            Pin::new(&mut self.inner).poll_write(cx, buf)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+        }
+    }
+
+    async fn seal(key: &Key, block_size: usize, message: &[u8]) -> Vec<u8> {
+        let sink = SharedBuf::new();
+        let mut w = AsyncEncryptingWriter::with_block_size(Flaky::new(sink.clone()), key, block_size).unwrap();
+        w.write_all(message).await.unwrap();
+        w.shutdown().await.unwrap();
+        sink.contents()
+    }
+
+    #[tokio::test]
+    async fn test_round_trip_with_pending_polls() {
+        ::init().unwrap();
+        let key = gen_key();
+        let message = b"a message split across several small blocks";
+        let ciphertext = seal(&key, 8, message).await;
+
+        let mut r = AsyncDecryptingReader::with_block_size(Flaky::new(&ciphertext[..]), key, 8).unwrap();
+        let mut plaintext = Vec::new();
+        r.read_to_end(&mut plaintext).await.unwrap();
+        assert_eq!(plaintext, &message[..]);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_stream_is_rejected() {
+        ::init().unwrap();
+        let key = gen_key();
+        let message = vec![0x42; 16];
+        let mut ciphertext = seal(&key, 8, &message).await;
+        let last = ciphertext.len() - 1;
+        ciphertext.truncate(last);
+
+        let mut r = AsyncDecryptingReader::with_block_size(Flaky::new(&ciphertext[..]), key, 8).unwrap();
+        let mut plaintext = Vec::new();
+        assert!(r.read_to_end(&mut plaintext).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tampered_block_is_rejected() {
+        ::init().unwrap();
+        let key = gen_key();
+        let message = vec![0x42; 16];
+        let mut ciphertext = seal(&key, 8, &message).await;
+        let last = ciphertext.len() - 1;
+        ciphertext[last] ^= 1;
+
+        let mut r = AsyncDecryptingReader::with_block_size(Flaky::new(&ciphertext[..]), key, 8).unwrap();
+        let mut plaintext = Vec::new();
+        assert!(r.read_to_end(&mut plaintext).await.is_err());
+    }
+
+    #[test]
+    fn test_zero_block_size_is_rejected() {
+        ::init().unwrap();
+        let key = gen_key();
+        assert!(AsyncEncryptingWriter::with_block_size(Vec::new(), &key, 0).is_err());
+        assert!(AsyncDecryptingReader::with_block_size(&b""[..], gen_key(), 0).is_err());
+    }
+}